@@ -1,13 +1,401 @@
 use std::collections::HashMap;
 use std::io::{ErrorKind, Read, Write};
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::{Duration, Instant};
 
+type Frame = HashMap<String, serde_bencode::value::Value>;
+
+/// Incrementally extracts complete top-level bencode dicts out of a byte
+/// stream that may contain several concatenated messages per `read()`, and
+/// carries any trailing partial message over to the next push. Rather than
+/// re-running `serde_bencode::from_bytes` over the whole buffer (which is
+/// O(n^2) for large values and throws away the buffer on any parse error,
+/// including "not enough data yet"), it walks bencode's self-delimiting
+/// grammar - `i...e`, `<len>:`, `l...e`, `d...e` - to find exactly where the
+/// first value ends, so a message is decoded once and only once.
+struct BencodeFramer {
+    buffer: Vec<u8>,
+}
+
+impl BencodeFramer {
+    fn new() -> Self {
+        BencodeFramer { buffer: Vec::new() }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Pops the next complete frame off the front of the buffer, if one has
+    /// fully arrived. Leftover bytes (the start of the next frame, or
+    /// nothing) remain buffered for the following call.
+    fn next_frame(&mut self) -> Result<Option<Frame>, NreplError> {
+        let end = match Self::scan_value_end(&self.buffer, 0) {
+            Some(end) => end,
+            None => {
+                // 1MB limit - guards against an unbounded buffer if the
+                // server never completes a value (or sends garbage).
+                if self.buffer.len() > 1024 * 1024 {
+                    return Err(NreplError::ParseError("Message too large".to_string()));
+                }
+                return Ok(None);
+            }
+        };
+
+        let frame_bytes: Vec<u8> = self.buffer.drain(..end).collect();
+        let frame = serde_bencode::from_bytes(&frame_bytes)
+            .map_err(|e| NreplError::ParseError(e.to_string()))?;
+        Ok(Some(frame))
+    }
+
+    /// Returns the offset just past the end of the bencode value starting at
+    /// `start`, or `None` if `buf[start..]` isn't a complete value yet.
+    fn scan_value_end(buf: &[u8], start: usize) -> Option<usize> {
+        match *buf.get(start)? {
+            b'i' => {
+                let end = Self::find(buf, start + 1, b'e')?;
+                Some(end + 1)
+            }
+            b'l' | b'd' => {
+                let mut pos = start + 1;
+                loop {
+                    if *buf.get(pos)? == b'e' {
+                        return Some(pos + 1);
+                    }
+                    pos = Self::scan_value_end(buf, pos)?;
+                }
+            }
+            b'0'..=b'9' => {
+                let colon = Self::find(buf, start, b':')?;
+                let len: usize = std::str::from_utf8(&buf[start..colon]).ok()?.parse().ok()?;
+                let data_start = colon + 1;
+                let data_end = data_start.checked_add(len)?;
+                if data_end > buf.len() {
+                    return None;
+                }
+                Some(data_end)
+            }
+            _ => None,
+        }
+    }
+
+    fn find(buf: &[u8], from: usize, target: u8) -> Option<usize> {
+        buf[from..].iter().position(|&b| b == target).map(|i| i + from)
+    }
+}
+
+/// Owns the socket and a background reader thread that demultiplexes
+/// incoming bencode frames by their `id` field, so several in-flight
+/// requests (e.g. a long `eval` and a concurrent `interrupt`) can share one
+/// transport without stepping on each other's responses.
+struct Connection {
+    writer: Mutex<Box<dyn NreplTransport>>,
+    pending: Arc<Mutex<HashMap<String, Sender<Frame>>>>,
+    reader: Option<thread::JoinHandle<()>>,
+}
+
+impl Connection {
+    fn connect(transport: Box<dyn NreplTransport>) -> Result<Arc<Self>, NreplError> {
+        let pending: Arc<Mutex<HashMap<String, Sender<Frame>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let reader_transport = transport.try_clone()?;
+        let reader_pending = Arc::clone(&pending);
+        let reader = thread::spawn(move || Connection::reader_loop(reader_transport, reader_pending));
+
+        Ok(Arc::new(Connection {
+            writer: Mutex::new(transport),
+            pending,
+            reader: Some(reader),
+        }))
+    }
+
+    /// Reads frames off the transport for the lifetime of the connection and
+    /// routes each one to the channel registered for its `id`. A frame
+    /// whose `id` nobody is waiting on (or that carries no `id` at all) is
+    /// dropped, since there's no pending caller left to deliver it to.
+    fn reader_loop(mut transport: Box<dyn NreplTransport>, pending: Arc<Mutex<HashMap<String, Sender<Frame>>>>) {
+        let mut framer = BencodeFramer::new();
+        let mut temp_buffer = [0u8; 4096];
+
+        loop {
+            match transport.read(&mut temp_buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    framer.push(&temp_buffer[..n]);
+
+                    // A single read can contain several concatenated frames
+                    // (e.g. fast `out` chunks coalesced by the kernel into
+                    // one segment) - drain all of them before reading again.
+                    loop {
+                        match framer.next_frame() {
+                            Ok(Some(frame)) => Connection::dispatch(&pending, frame),
+                            Ok(None) => break,
+                            Err(_) => {
+                                // Malformed frame - drop what's buffered and resync on the next read.
+                                framer.reset();
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+                Err(_) => break,
+            }
+        }
+
+        // Connection is gone: wake every waiting caller instead of leaving them hanging.
+        let mut pending = pending.lock().unwrap();
+        pending.clear();
+    }
+
+    fn dispatch(pending: &Arc<Mutex<HashMap<String, Sender<Frame>>>>, frame: Frame) {
+        let id = match frame.get("id") {
+            Some(serde_bencode::value::Value::Bytes(id_bytes)) => {
+                String::from_utf8_lossy(id_bytes).to_string()
+            }
+            _ => return,
+        };
+
+        let is_done = matches!(
+            frame.get("status"),
+            Some(serde_bencode::value::Value::List(list))
+                if list.iter().any(|s| matches!(s, serde_bencode::value::Value::Bytes(b) if b == b"done"))
+        );
+
+        let mut pending = pending.lock().unwrap();
+        if let Some(sender) = pending.get(&id) {
+            if sender.send(frame).is_err() || is_done {
+                pending.remove(&id);
+            }
+        }
+    }
+
+    /// Registers interest in frames for `id`, returning the channel they'll
+    /// arrive on. Must be called before the message carrying `id` is sent,
+    /// otherwise the reader thread may dispatch the first response before
+    /// anyone is listening for it.
+    fn register(&self, id: &str) -> Receiver<Frame> {
+        let (tx, rx) = mpsc::channel();
+        self.pending.lock().unwrap().insert(id.to_string(), tx);
+        rx
+    }
+
+    fn unregister(&self, id: &str) {
+        self.pending.lock().unwrap().remove(id);
+    }
+
+    fn send(&self, msg: &Frame) -> Result<(), NreplError> {
+        let encoded = serde_bencode::to_bytes(msg).map_err(|e| NreplError::ParseError(e.to_string()))?;
+        let mut writer = self.writer.lock().unwrap();
+
+        match writer.write_all(&encoded).and_then(|_| writer.flush()) {
+            Ok(_) => Ok(()),
+            Err(e) => match e.kind() {
+                ErrorKind::BrokenPipe | ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset => {
+                    Err(NreplError::ConnectionClosed)
+                }
+                _ => Err(NreplError::IoError(e)),
+            },
+        }
+    }
+
+    fn set_write_timeout(&self, timeout: Duration) -> Result<(), NreplError> {
+        self.writer.lock().unwrap().set_write_timeout(Some(timeout))?;
+        Ok(())
+    }
+
+    fn set_read_timeout(&self, timeout: Duration) -> Result<(), NreplError> {
+        self.writer.lock().unwrap().set_read_timeout(Some(timeout))?;
+        Ok(())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // Shutting down the socket unblocks the reader thread's blocking read.
+        if let Ok(writer) = self.writer.lock() {
+            let _ = writer.shutdown();
+        }
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+/// Abstracts the byte-stream endpoint underneath the bencode framing and op
+/// layer, so `Connection` doesn't care whether it's talking plain TCP or
+/// TLS. `NreplClient::connect` uses the `TcpStream` impl directly;
+/// `connect_tls` wraps the socket in rustls for remote/cloud nREPL servers
+/// that require encryption.
+trait NreplTransport: Read + Write + Send {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+    fn shutdown(&self) -> std::io::Result<()>;
+    fn try_clone(&self) -> std::io::Result<Box<dyn NreplTransport>>;
+}
+
+impl NreplTransport for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn shutdown(&self) -> std::io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+
+    fn try_clone(&self) -> std::io::Result<Box<dyn NreplTransport>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+}
+
+/// How long a single locked read on the shared TLS session is allowed to
+/// block. Keeping this short (rather than the minutes-long read timeout a
+/// caller might configure) means the reader thread only ever holds `inner`
+/// in brief slices, so `Connection::send` and the transport `shutdown` in
+/// `Connection`'s `Drop` - which need the same lock to write or tear down -
+/// never stall behind a long blocking read.
+const TLS_READ_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// TLS transport used by `connect_tls`. `rustls::StreamOwned` can't be split
+/// into independent read/write halves the way `TcpStream::try_clone` splits
+/// a raw socket, since both directions share one TLS session. Every clone
+/// here instead shares the same session behind a `Mutex`; reads are bounded
+/// by `TLS_READ_POLL_INTERVAL` so the lock is released between poll attempts
+/// instead of being held for the whole read timeout.
+struct TlsTransport {
+    inner: Arc<Mutex<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>>,
+}
+
+impl Read for TlsTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.lock().unwrap().read(buf)
+    }
+}
+
+impl Write for TlsTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.lock().unwrap().flush()
+    }
+}
+
+impl NreplTransport for TlsTransport {
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> std::io::Result<()> {
+        // Deliberately ignored: the real per-op timeout is enforced above
+        // this layer via `mpsc::Receiver::recv_timeout`, and the socket's
+        // own read timeout must stay at `TLS_READ_POLL_INTERVAL` so the
+        // reader thread keeps cycling the shared lock. Letting a caller
+        // stretch this out would reintroduce the write/shutdown stalls
+        // described on `TLS_READ_POLL_INTERVAL`.
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.inner.lock().unwrap().sock.set_write_timeout(timeout)
+    }
+
+    fn shutdown(&self) -> std::io::Result<()> {
+        self.inner.lock().unwrap().sock.shutdown(Shutdown::Both)
+    }
+
+    fn try_clone(&self) -> std::io::Result<Box<dyn NreplTransport>> {
+        Ok(Box::new(TlsTransport {
+            inner: Arc::clone(&self.inner),
+        }))
+    }
+}
+
+/// Backoff policy for automatically reconnecting after the connection is
+/// lost. Disabled by default - callers must opt in via
+/// `NreplClient::set_reconnect_policy`.
+pub struct ReconnectPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
 pub struct NreplClient {
-    stream: TcpStream,
-    session: Option<String>,
+    connection: Arc<Connection>,
+    session: Arc<Mutex<Option<String>>>,
     read_timeout: Duration,
     write_timeout: Duration,
+    host: String,
+    port: u16,
+    reconnect_policy: Option<ReconnectPolicy>,
+    retry_eval_on_reconnect: bool,
+    reconnect_callback: Option<Box<dyn FnMut() + Send>>,
+    stdin_provider: Option<Box<dyn FnMut() -> String + Send>>,
+    tls_config: Option<Arc<rustls::ClientConfig>>,
+}
+
+/// A lightweight, cloneable handle onto the same connection and session as
+/// the `NreplClient` it was obtained from. `NreplClient`'s own ops take
+/// `&mut self`, so nothing lets a caller interrupt an eval that's still
+/// streaming on the same client value - a handle fixes that by exposing the
+/// shared `Arc<Connection>` and session behind `&self`, so it can be cloned
+/// onto another thread and used concurrently with `eval`/`eval_streaming`
+/// running on the original client.
+#[derive(Clone)]
+pub struct NreplHandle {
+    connection: Arc<Connection>,
+    session: Arc<Mutex<Option<String>>>,
+}
+
+impl NreplHandle {
+    /// Sends an `interrupt` for the active session, if any, and waits for its
+    /// response. Unlike `NreplClient::interrupt`, this doesn't attempt a
+    /// reconnect on a closed connection - it's meant to run alongside a
+    /// longer-lived op on the client this handle was cloned from, which owns
+    /// that retry policy.
+    pub fn interrupt(&self) -> Result<(), NreplError> {
+        let session = match self.session.lock().unwrap().clone() {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut msg = HashMap::new();
+        msg.insert(
+            "op".to_string(),
+            serde_bencode::value::Value::Bytes(b"interrupt".to_vec()),
+        );
+        msg.insert(
+            "id".to_string(),
+            serde_bencode::value::Value::Bytes(id.clone().into_bytes()),
+        );
+        msg.insert(
+            "session".to_string(),
+            serde_bencode::value::Value::Bytes(session.into_bytes()),
+        );
+
+        let rx = self.connection.register(&id);
+        if let Err(e) = self.connection.send(&msg) {
+            self.connection.unregister(&id);
+            return Err(e);
+        }
+
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(_) => Ok(()),
+            Err(RecvTimeoutError::Timeout) => {
+                self.connection.unregister(&id);
+                Err(NreplError::Timeout)
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(NreplError::ConnectionClosed),
+        }
+    }
 }
 
 pub struct EvalResult {
@@ -17,6 +405,16 @@ pub struct EvalResult {
     pub has_error: bool,
 }
 
+/// One chunk of an in-progress eval, delivered to `eval_streaming`'s handler
+/// as soon as it arrives rather than buffered until `done`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalEvent {
+    Out(String),
+    Err(String),
+    Value(String),
+    Status(Vec<String>),
+}
+
 impl Default for EvalResult {
     fn default() -> Self {
         EvalResult {
@@ -59,9 +457,52 @@ impl From<std::io::Error> for NreplError {
 
 impl NreplClient {
     pub fn connect(host: &str, port: u16) -> Result<Self, NreplError> {
-        let stream = TcpStream::connect(format!("{}:{}", host, port))?;
+        let connection = Self::open_tcp_connection(host, port)?;
 
-        // Set timeouts
+        Ok(NreplClient {
+            connection,
+            session: Arc::new(Mutex::new(None)),
+            read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(10),
+            host: host.to_string(),
+            port,
+            reconnect_policy: None,
+            retry_eval_on_reconnect: false,
+            reconnect_callback: None,
+            stdin_provider: None,
+            tls_config: None,
+        })
+    }
+
+    /// Connects over TLS instead of plain TCP, for remote/cloud nREPL
+    /// servers that require an encrypted channel. `tls_config` carries
+    /// server cert verification and, for mutual TLS, a client cert/key -
+    /// build it the same way you would for any rustls client.
+    pub fn connect_tls(
+        host: &str,
+        port: u16,
+        tls_config: Arc<rustls::ClientConfig>,
+    ) -> Result<Self, NreplError> {
+        let connection = Self::open_tls_connection(host, port, Arc::clone(&tls_config))?;
+
+        Ok(NreplClient {
+            connection,
+            session: Arc::new(Mutex::new(None)),
+            read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(10),
+            host: host.to_string(),
+            port,
+            reconnect_policy: None,
+            retry_eval_on_reconnect: false,
+            reconnect_callback: None,
+            stdin_provider: None,
+            tls_config: Some(tls_config),
+        })
+    }
+
+    /// Applies the timeouts and keepalive settings every TCP-backed
+    /// transport needs, whether it ends up wrapped in TLS or used raw.
+    fn configure_tcp_stream(stream: &TcpStream) -> Result<(), NreplError> {
         stream.set_read_timeout(Some(Duration::from_secs(30)))?;
         stream.set_write_timeout(Some(Duration::from_secs(10)))?;
 
@@ -82,12 +523,50 @@ impl NreplClient {
             }
         }
 
-        Ok(NreplClient {
-            stream,
-            session: None,
-            read_timeout: Duration::from_secs(30),
-            write_timeout: Duration::from_secs(10),
-        })
+        Ok(())
+    }
+
+    fn open_tcp_connection(host: &str, port: u16) -> Result<Arc<Connection>, NreplError> {
+        let stream = TcpStream::connect(format!("{}:{}", host, port))?;
+        Self::configure_tcp_stream(&stream)?;
+        Connection::connect(Box::new(stream))
+    }
+
+    fn open_tls_connection(
+        host: &str,
+        port: u16,
+        tls_config: Arc<rustls::ClientConfig>,
+    ) -> Result<Arc<Connection>, NreplError> {
+        let stream = TcpStream::connect(format!("{}:{}", host, port))?;
+        Self::configure_tcp_stream(&stream)?;
+        // Overrides configure_tcp_stream's 30s read timeout: the reader
+        // thread holds the shared TLS session lock for the duration of each
+        // read, so it needs to poll in short slices rather than block for
+        // the full timeout (see TLS_READ_POLL_INTERVAL).
+        stream.set_read_timeout(Some(TLS_READ_POLL_INTERVAL))?;
+
+        let server_name = rustls::ServerName::try_from(host)
+            .map_err(|e| NreplError::Other(format!("invalid server name {}: {}", host, e)))?;
+        let client_conn = rustls::ClientConnection::new(tls_config, server_name)
+            .map_err(|e| NreplError::Other(format!("TLS setup failed: {}", e)))?;
+        let tls_stream = rustls::StreamOwned::new(client_conn, stream);
+
+        let transport: Box<dyn NreplTransport> = Box::new(TlsTransport {
+            inner: Arc::new(Mutex::new(tls_stream)),
+        });
+
+        Connection::connect(transport)
+    }
+
+    /// Re-opens a connection against the original host/port, over TLS again
+    /// if that's how this client originally connected.
+    fn reopen(&self) -> Result<Arc<Connection>, NreplError> {
+        match &self.tls_config {
+            Some(tls_config) => {
+                Self::open_tls_connection(&self.host, self.port, Arc::clone(tls_config))
+            }
+            None => Self::open_tcp_connection(&self.host, self.port),
+        }
     }
 
     pub fn set_timeouts(
@@ -97,12 +576,215 @@ impl NreplClient {
     ) -> Result<(), NreplError> {
         self.read_timeout = read_timeout;
         self.write_timeout = write_timeout;
-        self.stream.set_read_timeout(Some(read_timeout))?;
-        self.stream.set_write_timeout(Some(write_timeout))?;
+        self.connection.set_read_timeout(read_timeout)?;
+        self.connection.set_write_timeout(write_timeout)?;
         Ok(())
     }
 
+    /// Enables automatic reconnection: when a request fails with
+    /// `NreplError::ConnectionClosed`, the client re-`connect`s to the
+    /// original host/port (retrying up to `max_retries` times with
+    /// exponential backoff between `initial_backoff` and `max_backoff`)
+    /// and re-issues `clone_session` before retrying the failed op.
+    /// `eval`/`eval_with_timeout` do this only when
+    /// `set_retry_eval_on_reconnect(true)` is set; `eval_streaming` never
+    /// reconnects and returns the `ConnectionClosed` error as is.
+    pub fn set_reconnect_policy(
+        &mut self,
+        max_retries: u32,
+        initial_backoff: Duration,
+        max_backoff: Duration,
+    ) {
+        self.reconnect_policy = Some(ReconnectPolicy {
+            max_retries,
+            initial_backoff,
+            max_backoff,
+        });
+    }
+
+    /// Lets a caller know state was lost (session, in-flight streaming
+    /// output, etc.) whenever a reconnect actually happens.
+    pub fn set_reconnect_callback(&mut self, callback: impl FnMut() + Send + 'static) {
+        self.reconnect_callback = Some(Box::new(callback));
+    }
+
+    /// `eval`/`eval_with_timeout` only retry after a reconnect if this is
+    /// set, since an eval may have already produced side effects server-side
+    /// before the connection dropped and nREPL gives no resumption token -
+    /// retrying blindly could re-run them. Idempotent ops (`describe`,
+    /// `clone_session`, `interrupt`, `close`) always retry once reconnected.
+    pub fn set_retry_eval_on_reconnect(&mut self, enabled: bool) {
+        self.retry_eval_on_reconnect = enabled;
+    }
+
+    /// Supplies input for code that calls `(read-line)` or otherwise reads
+    /// stdin during an eval. Invoked each time the server reports
+    /// `need-input`; if no provider is set, an empty string is sent so the
+    /// server's blocked read unblocks with EOF instead of hanging until the
+    /// eval times out.
+    pub fn set_stdin_provider(&mut self, provider: impl FnMut() -> String + Send + 'static) {
+        self.stdin_provider = Some(Box::new(provider));
+    }
+
+    /// Convenience wrapper that feeds `inputs` to the server in order as it
+    /// asks for them, one line per `need-input`. Once `inputs` is exhausted,
+    /// further requests get an empty string (EOF). The provider is scoped to
+    /// this single eval; whatever was configured before (if anything) is
+    /// restored afterward instead of leaking into later evals.
+    pub fn eval_with_stdin(
+        &mut self,
+        code: &str,
+        inputs: Vec<String>,
+    ) -> Result<EvalResult, NreplError> {
+        let mut queue = inputs.into_iter();
+        let previous = self.stdin_provider.take();
+        self.set_stdin_provider(move || queue.next().unwrap_or_default());
+
+        let result = self.eval(code);
+
+        self.stdin_provider = previous;
+        result
+    }
+
+    /// Answers a `need-input` status by sending a `stdin` op on the active
+    /// session, reusing whatever provider is configured (or EOF if none is).
+    fn send_stdin(&mut self) -> Result<(), NreplError> {
+        let data = match self.stdin_provider.as_mut() {
+            Some(provider) => provider(),
+            None => String::new(),
+        };
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut msg = HashMap::new();
+        msg.insert(
+            "op".to_string(),
+            serde_bencode::value::Value::Bytes(b"stdin".to_vec()),
+        );
+        msg.insert(
+            "id".to_string(),
+            serde_bencode::value::Value::Bytes(id.into_bytes()),
+        );
+        msg.insert(
+            "stdin".to_string(),
+            serde_bencode::value::Value::Bytes(data.into_bytes()),
+        );
+        if let Some(session) = self.session.lock().unwrap().clone() {
+            msg.insert(
+                "session".to_string(),
+                serde_bencode::value::Value::Bytes(session.into_bytes()),
+            );
+        }
+
+        self.connection.send(&msg)
+    }
+
+    /// Tears down the current connection and session, then re-establishes
+    /// both against the original host/port per `self.reconnect_policy`.
+    fn reconnect(&mut self) -> Result<(), NreplError> {
+        let policy = self
+            .reconnect_policy
+            .as_ref()
+            .ok_or_else(|| NreplError::Other("Reconnect policy not set".to_string()))?;
+        let max_retries = policy.max_retries;
+        let mut backoff = policy.initial_backoff;
+        let max_backoff = policy.max_backoff;
+
+        let mut last_err = NreplError::ConnectionClosed;
+        for attempt in 0..=max_retries {
+            if attempt > 0 {
+                thread::sleep(backoff);
+                backoff = std::cmp::min(backoff * 2, max_backoff);
+            }
+
+            match self.reopen() {
+                Ok(connection) => {
+                    connection.set_read_timeout(self.read_timeout)?;
+                    connection.set_write_timeout(self.write_timeout)?;
+                    self.connection = connection;
+                    *self.session.lock().unwrap() = None;
+
+                    match self.clone_session_raw() {
+                        Ok(_) => {
+                            if let Some(callback) = self.reconnect_callback.as_mut() {
+                                callback();
+                            }
+                            return Ok(());
+                        }
+                        Err(e) => last_err = e,
+                    }
+                }
+                Err(e) => last_err = e,
+            }
+        }
+
+        Err(last_err)
+    }
+
+    /// Sends `msg` (whose `id` must already be set) and waits for exactly
+    /// one response carrying that `id`, honoring `self.read_timeout`.
+    fn call_raw(&mut self, msg: &Frame, id: &str) -> Result<Frame, NreplError> {
+        let rx = self.connection.register(id);
+
+        if let Err(e) = self.connection.send(msg) {
+            self.connection.unregister(id);
+            return Err(e);
+        }
+
+        match rx.recv_timeout(self.read_timeout) {
+            Ok(frame) => Ok(frame),
+            Err(RecvTimeoutError::Timeout) => {
+                self.connection.unregister(id);
+                Err(NreplError::Timeout)
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(NreplError::ConnectionClosed),
+        }
+    }
+
+    /// Like `call_raw`, but transparently reconnects and retries once if the
+    /// connection was closed and a reconnect policy is set. Used by the
+    /// idempotent ops, which are always safe to retry from scratch.
+    fn call(&mut self, mut msg: Frame, id: &str) -> Result<Frame, NreplError> {
+        match self.call_raw(&msg, id) {
+            Err(NreplError::ConnectionClosed) if self.reconnect_policy.is_some() => {
+                self.reconnect()?;
+
+                // `reconnect()` just replaced `self.session` with a fresh
+                // session id - if `msg` carries the old one (e.g. a
+                // session-bearing `interrupt`/`close`), rebind it so the
+                // retry doesn't target a session that no longer exists.
+                if msg.contains_key("session") {
+                    if let Some(session) = self.session.lock().unwrap().clone() {
+                        msg.insert(
+                            "session".to_string(),
+                            serde_bencode::value::Value::Bytes(session.into_bytes()),
+                        );
+                    }
+                }
+
+                self.call_raw(&msg, id)
+            }
+            other => other,
+        }
+    }
+
     pub fn clone_session(&mut self) -> Result<String, NreplError> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let msg = Self::clone_message(&id);
+        let response = self.call(msg, &id)?;
+        Self::session_from_clone_response(self, response)
+    }
+
+    /// Used internally by `reconnect()`: obtains a fresh session on the
+    /// just-reopened connection without going through the retrying `call()`
+    /// wrapper, which would otherwise recurse back into `reconnect()`.
+    fn clone_session_raw(&mut self) -> Result<String, NreplError> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let msg = Self::clone_message(&id);
+        let response = self.call_raw(&msg, &id)?;
+        Self::session_from_clone_response(self, response)
+    }
+
+    fn clone_message(id: &str) -> Frame {
         let mut msg = HashMap::new();
         msg.insert(
             "op".to_string(),
@@ -110,16 +792,19 @@ impl NreplClient {
         );
         msg.insert(
             "id".to_string(),
-            serde_bencode::value::Value::Bytes(uuid::Uuid::new_v4().to_string().into_bytes()),
+            serde_bencode::value::Value::Bytes(id.to_string().into_bytes()),
         );
+        msg
+    }
 
-        self.send_message(&msg)?;
-        let response = self.read_message_with_timeout()?;
-
+    fn session_from_clone_response(
+        &mut self,
+        response: Frame,
+    ) -> Result<String, NreplError> {
         if let Some(new_session) = response.get("new-session") {
             if let serde_bencode::value::Value::Bytes(session_bytes) = new_session {
                 let session_id = String::from_utf8_lossy(session_bytes).to_string();
-                self.session = Some(session_id.clone());
+                *self.session.lock().unwrap() = Some(session_id.clone());
                 return Ok(session_id);
             }
         }
@@ -139,12 +824,60 @@ impl NreplClient {
         timeout: Duration,
     ) -> Result<EvalResult, NreplError> {
         // Ensure we have a session
-        if self.session.is_none() {
+        if self.session.lock().unwrap().is_none() {
+            self.clone_session()?;
+        }
+
+        match self.eval_once(code, timeout) {
+            // An eval that hasn't produced any output may not have had a
+            // side effect yet, but we can't tell from here - only retry if
+            // the caller explicitly said that's safe for this code.
+            Err(NreplError::ConnectionClosed)
+                if self.retry_eval_on_reconnect && self.reconnect_policy.is_some() =>
+            {
+                self.reconnect()?;
+                self.eval_once(code, timeout)
+            }
+            other => other,
+        }
+    }
+
+    /// `eval_with_timeout`/`eval_once` built on top of `eval_streaming`,
+    /// buffering every event into one `EvalResult` the way they always have.
+    fn eval_once(&mut self, code: &str, timeout: Duration) -> Result<EvalResult, NreplError> {
+        let mut result = EvalResult::default();
+
+        self.eval_streaming(code, timeout, |event| match event {
+            EvalEvent::Value(value) => result.value = Some(value),
+            EvalEvent::Out(out) => result.output.push_str(&out),
+            EvalEvent::Err(err) => result.error.push_str(&err),
+            EvalEvent::Status(status) => {
+                if status.iter().any(|s| s == "error") {
+                    result.has_error = true;
+                }
+            }
+        })?;
+
+        Ok(result)
+    }
+
+    /// Evaluates `code`, invoking `handler` as each `out`/`err`/`value`/
+    /// `status` chunk arrives instead of buffering until `done`. This is
+    /// what lets a REPL UI render output live for long-running forms (e.g.
+    /// a loop with `Thread/sleep` and `println`) rather than blocking until
+    /// the whole thing finishes.
+    pub fn eval_streaming(
+        &mut self,
+        code: &str,
+        timeout: Duration,
+        mut handler: impl FnMut(EvalEvent),
+    ) -> Result<(), NreplError> {
+        if self.session.lock().unwrap().is_none() {
             self.clone_session()?;
         }
 
-        let mut msg = HashMap::new();
         let eval_id = uuid::Uuid::new_v4().to_string();
+        let mut msg = HashMap::new();
         msg.insert(
             "op".to_string(),
             serde_bencode::value::Value::Bytes(b"eval".to_vec()),
@@ -158,67 +891,70 @@ impl NreplClient {
             serde_bencode::value::Value::Bytes(code.as_bytes().to_vec()),
         );
 
-        if let Some(session) = &self.session {
+        if let Some(session) = self.session.lock().unwrap().clone() {
             msg.insert(
                 "session".to_string(),
-                serde_bencode::value::Value::Bytes(session.as_bytes().to_vec()),
+                serde_bencode::value::Value::Bytes(session.into_bytes()),
             );
         }
 
-        self.send_message(&msg)?;
+        let rx = self.connection.register(&eval_id);
+        if let Err(e) = self.connection.send(&msg) {
+            self.connection.unregister(&eval_id);
+            return Err(e);
+        }
 
-        let mut result = EvalResult::default();
-        let start_time = Instant::now();
+        let deadline = Instant::now() + timeout;
 
-        // Keep reading responses until we get "done" status or timeout
+        // Keep reading responses until we get "done" status or timeout. We no
+        // longer need to check the response id ourselves: the connection's
+        // reader thread only ever hands us frames registered under `eval_id`.
         loop {
-            if start_time.elapsed() > timeout {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                self.connection.unregister(&eval_id);
                 return Err(NreplError::Timeout);
             }
 
-            let response = match self.read_message_with_timeout() {
-                Ok(resp) => resp,
-                Err(NreplError::ConnectionClosed) => {
-                    return Err(NreplError::ConnectionClosed);
+            let response = match rx.recv_timeout(remaining) {
+                Ok(frame) => frame,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.connection.unregister(&eval_id);
+                    return Err(NreplError::Timeout);
                 }
-                Err(e) => return Err(e),
+                Err(RecvTimeoutError::Disconnected) => return Err(NreplError::ConnectionClosed),
             };
 
-            // Verify this response is for our request
-            if let Some(serde_bencode::value::Value::Bytes(id_bytes)) = response.get("id") {
-                let response_id = String::from_utf8_lossy(id_bytes);
-                if response_id != eval_id {
-                    continue; // Skip responses for other requests
-                }
-            }
-
-            // Extract value
             if let Some(serde_bencode::value::Value::Bytes(value_bytes)) = response.get("value") {
-                result.value = Some(String::from_utf8_lossy(value_bytes).to_string());
+                handler(EvalEvent::Value(String::from_utf8_lossy(value_bytes).to_string()));
             }
 
-            // Extract stdout
             if let Some(serde_bencode::value::Value::Bytes(out_bytes)) = response.get("out") {
-                result.output.push_str(&String::from_utf8_lossy(out_bytes));
+                handler(EvalEvent::Out(String::from_utf8_lossy(out_bytes).to_string()));
             }
 
-            // Extract stderr
             if let Some(serde_bencode::value::Value::Bytes(err_bytes)) = response.get("err") {
-                result.error.push_str(&String::from_utf8_lossy(err_bytes));
+                handler(EvalEvent::Err(String::from_utf8_lossy(err_bytes).to_string()));
             }
 
-            // Check status
             if let Some(serde_bencode::value::Value::List(status_list)) = response.get("status") {
-                let mut is_done = false;
-                for status_item in status_list {
-                    if let serde_bencode::value::Value::Bytes(status_bytes) = status_item {
-                        let status_str = String::from_utf8_lossy(status_bytes);
-                        match status_str.as_ref() {
-                            "done" => is_done = true,
-                            "error" => result.has_error = true,
-                            _ => {}
+                let statuses: Vec<String> = status_list
+                    .iter()
+                    .filter_map(|item| match item {
+                        serde_bencode::value::Value::Bytes(bytes) => {
+                            Some(String::from_utf8_lossy(bytes).to_string())
                         }
-                    }
+                        _ => None,
+                    })
+                    .collect();
+
+                let is_done = statuses.iter().any(|s| s == "done");
+                let needs_input = statuses.iter().any(|s| s == "need-input");
+
+                handler(EvalEvent::Status(statuses));
+
+                if needs_input {
+                    self.send_stdin()?;
                 }
                 if is_done {
                     break;
@@ -226,10 +962,11 @@ impl NreplClient {
             }
         }
 
-        Ok(result)
+        Ok(())
     }
 
     pub fn describe(&mut self) -> Result<HashMap<String, serde_bencode::value::Value>, NreplError> {
+        let id = uuid::Uuid::new_v4().to_string();
         let mut msg = HashMap::new();
         msg.insert(
             "op".to_string(),
@@ -237,15 +974,33 @@ impl NreplClient {
         );
         msg.insert(
             "id".to_string(),
-            serde_bencode::value::Value::Bytes(uuid::Uuid::new_v4().to_string().into_bytes()),
+            serde_bencode::value::Value::Bytes(id.clone().into_bytes()),
         );
 
-        self.send_message(&msg)?;
-        self.read_message_with_timeout()
+        self.call(msg, &id)
+    }
+
+    /// Returns a cloneable `NreplHandle` sharing this client's connection and
+    /// session. Use it to issue an `interrupt` from another thread while
+    /// `eval`/`eval_streaming` is still running here - `interrupt` itself
+    /// takes `&mut self`, so it can't be called concurrently with an
+    /// in-flight op on the same `NreplClient` value.
+    pub fn handle(&self) -> NreplHandle {
+        NreplHandle {
+            connection: Arc::clone(&self.connection),
+            session: Arc::clone(&self.session),
+        }
     }
 
+    /// Sends an `interrupt` for the active session. Responses are routed by
+    /// id, so the interrupt gets its own channel instead of fighting a
+    /// concurrent eval for the socket - but since this takes `&mut self`,
+    /// actually running one alongside a streaming eval requires a cloned
+    /// `handle()` on another thread rather than calling this directly.
     pub fn interrupt(&mut self) -> Result<(), NreplError> {
-        if let Some(session) = &self.session.clone() {
+        let session = self.session.lock().unwrap().clone();
+        if let Some(session) = session {
+            let id = uuid::Uuid::new_v4().to_string();
             let mut msg = HashMap::new();
             msg.insert(
                 "op".to_string(),
@@ -253,134 +1008,27 @@ impl NreplClient {
             );
             msg.insert(
                 "id".to_string(),
-                serde_bencode::value::Value::Bytes(uuid::Uuid::new_v4().to_string().into_bytes()),
+                serde_bencode::value::Value::Bytes(id.clone().into_bytes()),
             );
             msg.insert(
                 "session".to_string(),
-                serde_bencode::value::Value::Bytes(session.as_bytes().to_vec()),
+                serde_bencode::value::Value::Bytes(session.into_bytes()),
             );
 
-            self.send_message(&msg)?;
-            let _response = self.read_message_with_timeout()?;
+            self.call(msg, &id)?;
         }
         Ok(())
     }
 
     pub fn is_connected(&mut self) -> bool {
         // Try to send a small describe message to check connection
-        let mut msg = HashMap::new();
-        msg.insert(
-            "op".to_string(),
-            serde_bencode::value::Value::Bytes(b"describe".to_vec()),
-        );
-        msg.insert(
-            "id".to_string(),
-            serde_bencode::value::Value::Bytes(uuid::Uuid::new_v4().to_string().into_bytes()),
-        );
-
-        match self.send_message(&msg) {
-            Ok(_) => {
-                // Try to read response
-                match self.read_message_with_timeout() {
-                    Ok(_) => true,
-                    Err(_) => false,
-                }
-            }
-            Err(_) => false,
-        }
-    }
-
-    fn send_message(
-        &mut self,
-        msg: &HashMap<String, serde_bencode::value::Value>,
-    ) -> Result<(), NreplError> {
-        let encoded =
-            serde_bencode::to_bytes(msg).map_err(|e| NreplError::ParseError(e.to_string()))?;
-
-        // Try to write with timeout
-        match self.stream.write_all(&encoded) {
-            Ok(_) => match self.stream.flush() {
-                Ok(_) => Ok(()),
-                Err(e) => match e.kind() {
-                    ErrorKind::BrokenPipe
-                    | ErrorKind::ConnectionAborted
-                    | ErrorKind::ConnectionReset => Err(NreplError::ConnectionClosed),
-                    _ => Err(NreplError::IoError(e)),
-                },
-            },
-            Err(e) => match e.kind() {
-                ErrorKind::BrokenPipe
-                | ErrorKind::ConnectionAborted
-                | ErrorKind::ConnectionReset => Err(NreplError::ConnectionClosed),
-                _ => Err(NreplError::IoError(e)),
-            },
-        }
-    }
-
-    fn read_message_with_timeout(
-        &mut self,
-    ) -> Result<HashMap<String, serde_bencode::value::Value>, NreplError> {
-        let mut buffer = Vec::new();
-        let mut temp_buffer = [0u8; 4096];
-        let start_time = Instant::now();
-
-        loop {
-            if start_time.elapsed() > self.read_timeout {
-                return Err(NreplError::Timeout);
-            }
-
-            match self.stream.read(&mut temp_buffer) {
-                Ok(0) => {
-                    // Connection closed
-                    return Err(NreplError::ConnectionClosed);
-                }
-                Ok(n) => {
-                    buffer.extend_from_slice(&temp_buffer[..n]);
-
-                    // Try to decode what we have so far
-                    match serde_bencode::from_bytes::<HashMap<String, serde_bencode::value::Value>>(
-                        &buffer,
-                    ) {
-                        Ok(decoded) => return Ok(decoded),
-                        Err(_) => {
-                            // Need more data, continue reading
-                            // But check if we have too much data (potential attack)
-                            if buffer.len() > 1024 * 1024 {
-                                // 1MB limit
-                                return Err(NreplError::ParseError(
-                                    "Message too large".to_string(),
-                                ));
-                            }
-                            continue;
-                        }
-                    }
-                }
-                Err(e) => match e.kind() {
-                    ErrorKind::WouldBlock | ErrorKind::TimedOut => {
-                        if !buffer.is_empty() {
-                            // We have partial data, maybe try to decode it
-                            if let Ok(decoded) = serde_bencode::from_bytes::<
-                                HashMap<String, serde_bencode::value::Value>,
-                            >(&buffer)
-                            {
-                                return Ok(decoded);
-                            }
-                        }
-                        continue;
-                    }
-                    ErrorKind::UnexpectedEof
-                    | ErrorKind::ConnectionAborted
-                    | ErrorKind::ConnectionReset => {
-                        return Err(NreplError::ConnectionClosed);
-                    }
-                    _ => return Err(NreplError::IoError(e)),
-                },
-            }
-        }
+        self.describe().is_ok()
     }
 
     pub fn close(&mut self) -> Result<(), NreplError> {
-        if let Some(session) = &self.session.clone() {
+        let session = self.session.lock().unwrap().clone();
+        if let Some(session) = session {
+            let id = uuid::Uuid::new_v4().to_string();
             let mut msg = HashMap::new();
             msg.insert(
                 "op".to_string(),
@@ -388,17 +1036,16 @@ impl NreplClient {
             );
             msg.insert(
                 "id".to_string(),
-                serde_bencode::value::Value::Bytes(uuid::Uuid::new_v4().to_string().into_bytes()),
+                serde_bencode::value::Value::Bytes(id.clone().into_bytes()),
             );
             msg.insert(
                 "session".to_string(),
-                serde_bencode::value::Value::Bytes(session.as_bytes().to_vec()),
+                serde_bencode::value::Value::Bytes(session.into_bytes()),
             );
 
             // Best effort - don't fail if close fails
-            let _ = self.send_message(&msg);
-            let _ = self.read_message_with_timeout();
-            self.session = None;
+            let _ = self.call(msg, &id);
+            *self.session.lock().unwrap() = None;
         }
         Ok(())
     }
@@ -532,4 +1179,65 @@ mod tests {
             assert!(matches!(result, Err(NreplError::Timeout)));
         }
     }
+
+    #[test]
+    fn framer_splits_concatenated_messages_from_one_push() {
+        let mut framer = BencodeFramer::new();
+        framer.push(b"d1:ai1eed1:bi2ee");
+
+        let first = framer.next_frame().unwrap().unwrap();
+        assert_eq!(
+            first.get("a"),
+            Some(&serde_bencode::value::Value::Int(1))
+        );
+
+        let second = framer.next_frame().unwrap().unwrap();
+        assert_eq!(
+            second.get("b"),
+            Some(&serde_bencode::value::Value::Int(2))
+        );
+
+        assert!(framer.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn framer_retains_a_partial_message_across_pushes() {
+        let mut framer = BencodeFramer::new();
+        framer.push(b"d1:a");
+        assert!(framer.next_frame().unwrap().is_none());
+
+        framer.push(b"i1ee");
+        let frame = framer.next_frame().unwrap().unwrap();
+        assert_eq!(frame.get("a"), Some(&serde_bencode::value::Value::Int(1)));
+    }
+
+    #[test]
+    fn framer_scans_past_nested_lists_and_dicts() {
+        let mut framer = BencodeFramer::new();
+        // {"a": ["x", "y"], "b": {"c": 1}}
+        framer.push(b"d1:al1:x1:ye1:bd1:ci1eee");
+
+        let frame = framer.next_frame().unwrap().unwrap();
+        match frame.get("a") {
+            Some(serde_bencode::value::Value::List(items)) => assert_eq!(items.len(), 2),
+            other => panic!("expected a list for \"a\", got {:?}", other),
+        }
+        match frame.get("b") {
+            Some(serde_bencode::value::Value::Dict(inner)) => assert_eq!(inner.len(), 1),
+            other => panic!("expected a dict for \"b\", got {:?}", other),
+        }
+
+        assert!(framer.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn framer_rejects_a_message_that_never_completes() {
+        let mut framer = BencodeFramer::new();
+        // An unterminated integer looks exactly like a partial message
+        // forever, so this is what actually exercises the 1MB overflow guard.
+        framer.push(b"i");
+        framer.push(&vec![b'1'; 2 * 1024 * 1024]);
+
+        assert!(matches!(framer.next_frame(), Err(NreplError::ParseError(_))));
+    }
 }